@@ -10,7 +10,7 @@
 
 //! Basic floating-point number distributions
 
-use core::mem;
+use core::ops::{Add, Sub, Mul, Div, BitAnd, BitOr};
 use Rng;
 use distributions::{Distribution, Uniform};
 
@@ -55,8 +55,153 @@ pub struct Open01;
 #[derive(Clone, Copy, Debug)]
 pub struct Closed01;
 
+/// A distribution to sample floating point numbers uniformly in the half-open
+/// interval `[0, 1)` at *full* floating-point precision.
+///
+/// [`Uniform`] only randomises the mantissa, so every value it returns is a
+/// multiple of `2^-23` (`f32`) or `2^-52` (`f64`) and tiny values such as
+/// `1e-10` can never appear. `HighPrecision01` can instead return any *normal*
+/// float in `[0, 1)` down to the smallest normal (`2^-126`/`2^-1022`), picking
+/// each one with probability proportional to the width of the real interval
+/// that rounds to it (the subnormal range is folded into the smallest normal
+/// interval by the exponent clamp). This is
+/// what you want when the sample is later fed through a steep transform such
+/// as `-ln(u)` (as in the exponential and normal generators), where the
+/// coarse spacing near zero would otherwise be visible.
+///
+/// It is implemented with Downey's method: the binary exponent is drawn first
+/// (decrementing once per leading zero bit), then a uniform mantissa is drawn
+/// for the chosen interval.
+///
+/// # Example
+/// ```rust
+/// use rand::{weak_rng, Rng};
+/// use rand::distributions::HighPrecision01;
+///
+/// let val: f64 = weak_rng().sample(HighPrecision01);
+/// println!("f64 from [0,1): {}", val);
+/// ```
+///
+/// [`Uniform`]: struct.Uniform.html
+#[derive(Clone, Copy, Debug)]
+pub struct HighPrecision01;
+
+/// A distribution to sample floating point numbers uniformly in a half-open
+/// interval `[low, high)`, or the closed interval `[low, high]` when built
+/// with [`new_inclusive`].
+///
+/// The canonical `[0, 1)` value produced by [`Uniform`] (or, for
+/// [`new_inclusive`], the `[0, 1]` value produced by [`Closed01`]) is rescaled
+/// onto the requested interval as `low + x * (high - low)`, with the scale and
+/// offset precomputed at construction so that each draw is a single
+/// multiply-add; a draw that rounds outside the interval is redrawn.
+/// Negative and mixed-sign ranges are handled, as is the degenerate
+/// `low == high`, which always yields `low`. When `high - low` overflows to
+/// infinity for extreme magnitudes, sampling falls back to interpolating
+/// between the endpoints so that no intermediate infinity is produced.
+///
+/// # Example
+/// ```rust
+/// use rand::{weak_rng, Rng};
+/// use rand::distributions::UniformFloat;
+///
+/// let d = UniformFloat::new(-2.0, 7.0);
+/// let val: f64 = weak_rng().sample(d);
+/// println!("f64 from [-2, 7): {}", val);
+/// ```
+///
+/// [`Uniform`]: struct.Uniform.html
+/// [`Closed01`]: struct.Closed01.html
+/// [`new_inclusive`]: struct.UniformFloat.html#method.new_inclusive
+#[derive(Clone, Copy, Debug)]
+pub struct UniformFloat<T> {
+    offset: T,
+    scale: T,
+    high: T,
+    inclusive: bool,
+}
+
+impl<T: FloatConversions + PartialOrd> UniformFloat<T> {
+    /// Create a distribution sampling uniformly from the half-open
+    /// interval `[low, high)`.
+    ///
+    /// # Panics
+    /// Panics if `low > high`.
+    pub fn new(low: T, high: T) -> UniformFloat<T> {
+        UniformFloat::build(low, high, false)
+    }
+
+    /// Create a distribution sampling uniformly from the closed
+    /// interval `[low, high]`.
+    ///
+    /// # Panics
+    /// Panics if `low > high`.
+    pub fn new_inclusive(low: T, high: T) -> UniformFloat<T> {
+        UniformFloat::build(low, high, true)
+    }
+
+    fn build(low: T, high: T, inclusive: bool) -> UniformFloat<T> {
+        assert!(low <= high, "UniformFloat::new called with low > high");
+        UniformFloat {
+            offset: low,
+            scale: high - low,
+            high,
+            inclusive,
+        }
+    }
+}
 
-// Return the next random f32 selected from the half-open
+
+/// Bit-level conversions and constants that let the unit-interval float
+/// distributions ([`Open01`], [`Closed01`] and [`Uniform`]) operate over any
+/// floating point type, not just the built-in `f32` and `f64`.
+///
+/// Downstream crates can implement this for their own float-like types
+/// (fixed-point wrappers, `half::f16`, SIMD lanes, ...) to obtain uniform,
+/// open and closed unit-interval sampling for free; only the members below
+/// need supplying, as [`gen_one_two`] is derived from them.
+///
+/// [`Open01`]: struct.Open01.html
+/// [`Closed01`]: struct.Closed01.html
+/// [`Uniform`]: struct.Uniform.html
+/// [`gen_one_two`]: trait.FloatConversions.html#method.gen_one_two
+pub trait FloatConversions
+    : Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self>
+    + Mul<Output = Self> + Div<Output = Self>
+{
+    /// The unsigned integer type with the same bit width as this float.
+    type UInt: Copy + BitAnd<Output = Self::UInt> + BitOr<Output = Self::UInt>;
+
+    /// The constant `0.5`.
+    const HALF: Self;
+    /// The constant `1.0`.
+    const ONE: Self;
+    /// `2^(mantissa bits)`, the scale the mantissa is measured against.
+    const SCALE: Self;
+
+    /// The exponent bit pattern selecting the half-open interval `[1, 2)`
+    /// (the exponent bias shifted above the mantissa).
+    fn exponent_mask() -> Self::UInt;
+    /// A mask selecting the mantissa (fraction) bits.
+    fn mantissa_mask() -> Self::UInt;
+    /// Draw a uniformly distributed value of the associated integer type.
+    fn gen_uint<R: Rng + ?Sized>(rng: &mut R) -> Self::UInt;
+    /// Reinterpret the given bit pattern as a float of this type.
+    fn from_bits(bits: Self::UInt) -> Self;
+
+    /// Draw a float uniformly from the half-open interval `[1, 2)`, the shared
+    /// core of the unit-interval distributions. See the comment on the
+    /// [`Uniform`] impl for the technique.
+    ///
+    /// [`Uniform`]: struct.Uniform.html
+    #[inline]
+    fn gen_one_two<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let bits = Self::exponent_mask() | (Self::gen_uint(rng) & Self::mantissa_mask());
+        Self::from_bits(bits)
+    }
+}
+
+// Return the next random float selected from the half-open
 // interval `[0, 1)`.
 //
 // This uses a technique described by Saito and Matsumoto at
@@ -74,65 +219,181 @@ pub struct Closed01;
 //
 // * <http://www.math.sci.hiroshima-u.ac.jp/~m-mat/MT/ARTICLES/dSFMT.pdf>
 // * <http://www.math.sci.hiroshima-u.ac.jp/~m-mat/MT/SFMT/dSFMT-slide-e.pdf>
-impl Distribution<f32> for Uniform {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
-        const UPPER_MASK: u32 = 0x3F800000;
-        const LOWER_MASK: u32 = 0x7FFFFF;
-        let tmp = UPPER_MASK | (rng.next_u32() & LOWER_MASK);
-        let result: f32 = unsafe { mem::transmute(tmp) };
-        result - 1.0
+impl<T: FloatConversions> Distribution<T> for Uniform {
+    #[inline]
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> T {
+        T::gen_one_two(rng) - T::ONE
     }
 }
-impl Distribution<f64> for Uniform {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
-        const UPPER_MASK: u64 = 0x3FF0000000000000;
-        const LOWER_MASK: u64 = 0xFFFFFFFFFFFFF;
-        let tmp = UPPER_MASK | (rng.next_u64() & LOWER_MASK);
-        let result: f64 = unsafe { mem::transmute(tmp) };
-        result - 1.0
+
+impl<T: FloatConversions> Distribution<T> for Open01 {
+    #[inline]
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> T {
+        // add 0.5 * epsilon, so that smallest number is
+        // greater than 0, and largest number is still
+        // less than 1, specifically 1 - 0.5 * epsilon.
+        let x = T::gen_one_two(rng) - T::ONE;
+        x + T::HALF / T::SCALE
+    }
+}
+
+impl<T: FloatConversions> Distribution<T> for Closed01 {
+    #[inline]
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> T {
+        // rescale so that 1.0 - epsilon becomes 1.0
+        // precisely.
+        let x = T::gen_one_two(rng) - T::ONE;
+        x * T::SCALE / (T::SCALE - T::ONE)
     }
 }
 
 macro_rules! float_impls {
-    ($mod_name:ident, $ty:ty, $mantissa_bits:expr) => {
+    ($mod_name:ident, $ty:ty, $uty:ty, $mantissa_bits:expr, $bias:expr) => {
         mod $mod_name {
             use Rng;
-            use distributions::{Distribution};
-            use super::{Open01, Closed01};
+            use distributions::Distribution;
+            use super::{Closed01, HighPrecision01, UniformFloat, FloatConversions};
 
             const SCALE: $ty = (1u64 << $mantissa_bits) as $ty;
 
-            impl Distribution<$ty> for Open01 {
+            impl FloatConversions for $ty {
+                type UInt = $uty;
+
+                const HALF: $ty = 0.5;
+                const ONE: $ty = 1.0;
+                const SCALE: $ty = (1u64 << $mantissa_bits) as $ty;
+
                 #[inline]
+                fn exponent_mask() -> $uty { ($bias as $uty) << $mantissa_bits }
+                #[inline]
+                fn mantissa_mask() -> $uty { ((1 as $uty) << $mantissa_bits) - 1 }
+                #[inline]
+                fn gen_uint<R: Rng + ?Sized>(rng: &mut R) -> $uty { rng.gen::<$uty>() }
+                #[inline]
+                fn from_bits(bits: $uty) -> $ty { <$ty>::from_bits(bits) }
+            }
+
+            impl Distribution<$ty> for UniformFloat<$ty> {
                 fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> $ty {
-                    // add 0.5 * epsilon, so that smallest number is
-                    // greater than 0, and largest number is still
-                    // less than 1, specifically 1 - 0.5 * epsilon.
-                    let x: $ty = rng.gen();
-                    x + 0.5 / SCALE
+                    if !self.scale.is_finite() {
+                        // `high - low` overflowed, so the affine form would
+                        // yield infinities. Interpolate between the endpoints
+                        // instead, keeping every intermediate finite, and
+                        // reject the rare rounding that escapes the interval.
+                        loop {
+                            let x: $ty = if self.inclusive {
+                                rng.sample(Closed01)
+                            } else {
+                                rng.gen()
+                            };
+                            let v = self.offset * (1.0 - x) + self.high * x;
+                            let in_range = v >= self.offset
+                                && if self.inclusive { v <= self.high } else { v < self.high };
+                            if in_range {
+                                return v;
+                            }
+                        }
+                    } else if self.inclusive {
+                        // `[low, high]`: a multiply-add off the canonical
+                        // `[0, 1]` value, rejecting the rare rounding of
+                        // `low + (high - low)` that lands above `high`.
+                        loop {
+                            let x: $ty = rng.sample(Closed01);
+                            let v = self.offset + x * self.scale;
+                            if v <= self.high {
+                                return v;
+                            }
+                        }
+                    } else {
+                        // `[low, high)`: a degenerate range is just `low`;
+                        // otherwise reject the rare rounding that lands exactly
+                        // on `high` so the interval stays half-open.
+                        if self.offset == self.high {
+                            return self.offset;
+                        }
+                        loop {
+                            let x: $ty = rng.gen();
+                            let v = self.offset + x * self.scale;
+                            if v < self.high {
+                                return v;
+                            }
+                        }
+                    }
                 }
             }
-            impl Distribution<$ty> for Closed01 {
-                #[inline]
+
+            impl Distribution<$ty> for HighPrecision01 {
                 fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> $ty {
-                    // rescale so that 1.0 - epsilon becomes 1.0
-                    // precisely.
-                    let x: $ty = rng.gen();
-                    x * SCALE / (SCALE - 1.0)
+                    // The most negative exponent we will generate. Below this
+                    // we would enter the subnormal range; instead we clamp,
+                    // folding the subnormals into the smallest normal
+                    // interval.
+                    const MIN_EXPONENT: i32 = 1 - $bias;
+
+                    // The draw can reject (only when a carry would land on
+                    // exactly `1.0`), so the whole thing lives in a loop.
+                    loop {
+                        // Draw the exponent `e`. Starting at -1 it is
+                        // decremented once for every zero bit seen before the
+                        // first one bit, making it -1 with probability 1/2, -2
+                        // with probability 1/4, and so on (Downey's method).
+                        // Random bits are consumed one word at a time until a
+                        // one bit is found or the subnormal boundary is reached.
+                        let mut e: i32 = -1;
+                        'exponent: loop {
+                            let mut bits: $uty = rng.gen();
+                            for _ in 0..(::core::mem::size_of::<$uty>() * 8) {
+                                if bits & 1 != 0 {
+                                    break 'exponent;
+                                }
+                                e -= 1;
+                                if e <= MIN_EXPONENT {
+                                    e = MIN_EXPONENT;
+                                    break 'exponent;
+                                }
+                                bits >>= 1;
+                            }
+                        }
+
+                        // Uniform mantissa in `[0, 2^$mantissa_bits)`.
+                        let mantissa: $uty =
+                            rng.gen::<$uty>() & (((1 as $uty) << $mantissa_bits) - 1);
+
+                        // A zero mantissa places the value exactly on the
+                        // boundary `2^e`, shared with the top of the next
+                        // interval down. Always flip a coin to carry into
+                        // `e + 1`, so each power-of-two boundary keeps the
+                        // correct half of the `m == 0` mass. A carry out of the
+                        // topmost interval would produce exactly `1.0`, which
+                        // is outside `[0, 1)`; reject and redraw in that case so
+                        // the coin is still flipped and `0.5` keeps its density.
+                        if mantissa == 0 && (rng.next_u32() & 1) == 1 {
+                            if e == -1 {
+                                continue;
+                            }
+                            e += 1;
+                        }
+
+                        // Assemble `(1 + mantissa / 2^$mantissa_bits) * 2^e`.
+                        // The power of two is built straight from its bit
+                        // pattern so this stays `no_std`-friendly and exact.
+                        let pow2 = <$ty>::from_bits(((e + $bias) as $uty) << $mantissa_bits);
+                        return (1.0 + (mantissa as $ty) / SCALE) * pow2;
+                    }
                 }
             }
         }
     }
 }
-float_impls! { f64_rand_impls, f64, 52 }
-float_impls! { f32_rand_impls, f32, 23 }
+float_impls! { f64_rand_impls, f64, u64, 52, 1023 }
+float_impls! { f32_rand_impls, f32, u32, 23, 127 }
 
 
 #[cfg(test)]
 mod tests {
     use Rng;
     use mock::StepRng;
-    use distributions::{Open01, Closed01};
+    use distributions::{Open01, Closed01, HighPrecision01, UniformFloat};
 
     const EPSILON32: f32 = ::core::f32::EPSILON;
     const EPSILON64: f64 = ::core::f64::EPSILON;
@@ -213,4 +474,124 @@ mod tests {
             assert!(0.0 <= f && f <= 1.0);
         }
     }
+
+    #[test]
+    fn rand_high_precision() {
+        let mut rng = ::test::rng(512);
+        for _ in 0..1_000 {
+            // half-open interval, 1.0 must never be produced
+            let f: f64 = rng.sample(HighPrecision01);
+            assert!(0.0 <= f && f < 1.0);
+
+            let f: f32 = rng.sample(HighPrecision01);
+            assert!(0.0 <= f && f < 1.0);
+        }
+    }
+
+    // A scripted `Rng` whose `next_u32` and `next_u64` are driven by
+    // independent queues, unlike `StepRng` where `next_u32` is derived from
+    // the same counter as `next_u64`. `HighPrecision01::sample` draws the
+    // exponent bits, the mantissa and the carry coin as separate calls, so
+    // pinning the exponent/mantissa/coin boundary case requires controlling
+    // each draw independently.
+    struct ScriptedRng {
+        u64s: Vec<u64>,
+        u64_pos: usize,
+        u32s: Vec<u32>,
+        u32_pos: usize,
+    }
+
+    impl ScriptedRng {
+        fn new(u64s: Vec<u64>, u32s: Vec<u32>) -> ScriptedRng {
+            ScriptedRng { u64s, u64_pos: 0, u32s, u32_pos: 0 }
+        }
+    }
+
+    impl Rng for ScriptedRng {
+        fn next_u32(&mut self) -> u32 {
+            let v = self.u32s[self.u32_pos];
+            self.u32_pos += 1;
+            v
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let v = self.u64s[self.u64_pos];
+            self.u64_pos += 1;
+            v
+        }
+    }
+
+    #[test]
+    fn high_precision_rejects_carry_past_top_interval() {
+        // First draw: the exponent loop sees a `1` bit immediately (`e`
+        // stays `-1`), the mantissa is `0` (landing exactly on the `2^e`
+        // boundary), and the carry coin comes up heads. Carrying out of the
+        // topmost interval would produce exactly `1.0`, so this draw must be
+        // rejected and redrawn rather than returned.
+        //
+        // Second draw: exponent bits `1` again (`e = -1`), nonzero mantissa,
+        // so no coin flip is needed and the loop returns.
+        let mut rng = ScriptedRng::new(
+            vec![1, 0, 1, 5],
+            vec![1],
+        );
+        let f: f64 = rng.sample(HighPrecision01);
+        assert!(0.0 <= f && f < 1.0);
+    }
+
+    #[test]
+    fn uniform_float_ranges() {
+        let mut rng = ::test::rng(513);
+        let half_open = UniformFloat::new(-2.0f64, 7.0);
+        let closed = UniformFloat::new_inclusive(-2.0f64, 7.0);
+        for _ in 0..1_000 {
+            let f = rng.sample(half_open);
+            assert!(-2.0 <= f && f < 7.0);
+
+            let f = rng.sample(closed);
+            assert!(-2.0 <= f && f <= 7.0);
+        }
+    }
+
+    #[test]
+    fn uniform_float_degenerate() {
+        let mut rng = ::test::rng(514);
+        let point = UniformFloat::new(3.5f32, 3.5);
+        for _ in 0..100 {
+            assert_eq!(rng.sample(point), 3.5);
+        }
+    }
+
+    #[test]
+    fn uniform_float_overflowing_range_stays_finite() {
+        // `high - low` overflows to infinity here, forcing the two-sample
+        // interpolation-and-reject path rather than the usual
+        // multiply-add.
+        let mut rng = ::test::rng(515);
+        let half_open = UniformFloat::new(::core::f64::MIN, ::core::f64::MAX);
+        let closed = UniformFloat::new_inclusive(::core::f64::MIN, ::core::f64::MAX);
+        for _ in 0..1_000 {
+            let f = rng.sample(half_open);
+            assert!(f.is_finite() && ::core::f64::MIN <= f && f < ::core::f64::MAX);
+
+            let f = rng.sample(closed);
+            assert!(f.is_finite() && ::core::f64::MIN <= f && f <= ::core::f64::MAX);
+        }
+    }
+
+    #[test]
+    fn uniform_float_rejects_rounding_onto_high() {
+        // `high - low` is exactly `EPSILON64` here, the smallest increment
+        // representable just above `1.0`. The largest value `Uniform` can
+        // draw below `1.0` (all-ones mantissa bits, per
+        // `floating_point_edge_cases` above), when folded back through
+        // `offset + x * scale`, rounds exactly onto `high` unless that draw
+        // is rejected and redrawn (see `7ed2916`). A constant-returning RNG
+        // can never escape that rejection loop, so script the first draw to
+        // hit the boundary and the second to clear it.
+        let mut rng = ScriptedRng::new(vec![!0u64, 0u64], vec![]);
+        let d = UniformFloat::new(1.0f64, 1.0 + EPSILON64);
+        let v = rng.sample(d);
+        assert!(v < 1.0 + EPSILON64);
+    }
 }